@@ -1,89 +1,319 @@
-use std::sync::Arc;
-
 use anyhow::Context;
 use serenity::{
     model::prelude::{ChannelId, GuildId, MessageId},
     prelude::TypeMapKey,
 };
-use tokio::sync::{Mutex, MutexGuard};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
 
-use crate::types::Subscription;
+use crate::types::{GuildSetting, GuildSettings, PlayerCountRecord, Subscription};
 
 #[derive(Clone)]
-pub struct BotDb(Arc<Mutex<rusqlite::Connection>>);
+pub struct BotDb(SqlitePool);
 
 impl BotDb {
-    pub fn new(db_path: &str) -> Self {
-        let conn = rusqlite::Connection::open(db_path).unwrap();
-        Self(Arc::new(Mutex::new(conn)))
-    }
-
-    async fn conn(&self) -> MutexGuard<'_, rusqlite::Connection> {
-        self.0.lock().await
+    pub async fn new(db_path: &str) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(db_path)
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .context("Failed to open database")?;
+        Ok(Self(pool))
     }
 
     pub async fn migrate(&self) -> anyhow::Result<()> {
-        const INIT_SQL: &'static str = include_str!("./init.sql");
-        let conn = self.conn().await;
-        conn.execute_batch(INIT_SQL)
+        sqlx::migrate!()
+            .run(&self.0)
+            .await
             .context("Failed to migrate database")?;
-
         Ok(())
     }
 
     pub async fn upsert_subscription(&self, sub: Subscription) -> anyhow::Result<()> {
-        let conn = self.conn().await;
-        let mut stmt = conn.prepare_cached(
-            "INSERT INTO subscriptions (guild_id, channel_id, message_id, server_hostname)
-            VALUES (?, ?, ?, ?)
-            ON CONFLICT (channel_id, server_hostname) DO UPDATE SET server_hostname = ?, message_id = ?",
-        )?;
-        stmt.execute((
-            sub.guild_id.0,
-            sub.channel_id.0,
-            sub.message_id.0,
-            sub.server_hostname.to_string(),
-            sub.server_hostname.to_string(),
-            sub.message_id.0,
-        ))?;
+        let guild_id = sub.guild_id.0 as i64;
+        let channel_id = sub.channel_id.0 as i64;
+        let message_id = sub.message_id.0 as i64;
+        let game = sub.game.as_str();
+        let notify_players = sub.notify_players as i64;
+        sqlx::query!(
+            "INSERT INTO subscriptions (guild_id, channel_id, message_id, server_hostname, game, notify_players)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (channel_id, server_hostname) DO UPDATE SET message_id = ?, game = ?",
+            guild_id,
+            channel_id,
+            message_id,
+            sub.server_hostname,
+            game,
+            notify_players,
+            message_id,
+            game,
+        )
+        .execute(&self.0)
+        .await?;
         Ok(())
     }
 
     pub async fn delete_subscriptions_by_channel_id(
         &self,
         channel_id: ChannelId,
-    ) -> anyhow::Result<usize> {
-        let conn = self.conn().await;
-        let mut stmt = conn.prepare_cached("DELETE FROM subscriptions WHERE channel_id = ?")?;
-        let changes = stmt.execute((channel_id.0,))?;
-        Ok(changes)
+    ) -> anyhow::Result<u64> {
+        let channel_id = channel_id.0 as i64;
+        let result = sqlx::query!("DELETE FROM subscriptions WHERE channel_id = ?", channel_id)
+            .execute(&self.0)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Persists the outage-tracking state after a poll so backoff and
+    /// transition detection survive restarts.
+    pub async fn update_outage_state(
+        &self,
+        id: i64,
+        last_status: bool,
+        consecutive_failures: u32,
+        last_success_at: Option<i64>,
+    ) -> anyhow::Result<()> {
+        let last_status = last_status as i64;
+        let consecutive_failures = consecutive_failures as i64;
+        sqlx::query!(
+            "UPDATE subscriptions SET last_status = ?, consecutive_failures = ?, last_success_at = ? WHERE id = ?",
+            last_status,
+            consecutive_failures,
+            last_success_at,
+            id,
+        )
+        .execute(&self.0)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_notify_players_by_channel(
+        &self,
+        channel_id: ChannelId,
+        enabled: bool,
+    ) -> anyhow::Result<u64> {
+        let channel_id = channel_id.0 as i64;
+        let enabled = enabled as i64;
+        let result = sqlx::query!(
+            "UPDATE subscriptions SET notify_players = ? WHERE channel_id = ?",
+            enabled,
+            channel_id,
+        )
+        .execute(&self.0)
+        .await?;
+        Ok(result.rows_affected())
     }
 
-    pub async fn delete_subscription_by_id(&self, id: i64) -> anyhow::Result<usize> {
-        let conn = self.conn().await;
-        let mut stmt = conn.prepare_cached("DELETE FROM subscriptions WHERE id = ?")?;
-        let changes = stmt.execute((id,))?;
-        Ok(changes)
+    pub async fn delete_subscription_by_id(&self, id: i64) -> anyhow::Result<u64> {
+        let result = sqlx::query!("DELETE FROM subscriptions WHERE id = ?", id)
+            .execute(&self.0)
+            .await?;
+        Ok(result.rows_affected())
     }
 
     pub async fn get_subscriptions(&self) -> anyhow::Result<Vec<Subscription>> {
-        let conn = self.conn().await;
-        let mut stmt = conn.prepare_cached(
-            "SELECT id, guild_id, channel_id, message_id, server_hostname FROM subscriptions",
-        )?;
-        let mut rows = stmt.query(())?;
-        let mut subs = Vec::new();
-        while let Some(row) = rows.next()? {
-            let sub = Subscription {
-                id: Some(row.get(0)?),
-                guild_id: GuildId(row.get(1)?),
-                channel_id: ChannelId(row.get(2)?),
-                message_id: MessageId(row.get(3)?),
-                server_hostname: row.get::<_, String>(4)?.parse()?,
-            };
-            subs.push(sub);
+        // SQLite nullability inference reports ALTER-added columns as nullable
+        // even though they are declared NOT NULL, so force them back to
+        // non-null with sqlx's `!` override to keep the casts below honest.
+        let rows = sqlx::query!(
+            "SELECT id, guild_id, channel_id, message_id, server_hostname, game, \
+             notify_players AS \"notify_players!: i64\", \
+             consecutive_failures AS \"consecutive_failures!: i64\", \
+             last_status AS \"last_status!: i64\", last_success_at FROM subscriptions"
+        )
+        .fetch_all(&self.0)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(Subscription {
+                    id: Some(row.id),
+                    guild_id: GuildId(row.guild_id as u64),
+                    channel_id: ChannelId(row.channel_id as u64),
+                    message_id: MessageId(row.message_id as u64),
+                    server_hostname: row.server_hostname,
+                    game: row.game.parse()?,
+                    notify_players: row.notify_players != 0,
+                    consecutive_failures: row.consecutive_failures as u32,
+                    last_status: row.last_status != 0,
+                    last_success_at: row.last_success_at,
+                })
+            })
+            .collect()
+    }
+    /// Returns the last-seen roster for a subscription as `(name, missing_polls)`
+    /// pairs, where `missing_polls` is the number of consecutive polls the
+    /// player has been absent.
+    pub async fn get_roster(&self, subscription_id: i64) -> anyhow::Result<Vec<(String, i64)>> {
+        let rows = sqlx::query!(
+            "SELECT player_name, missing_polls FROM subscription_players WHERE subscription_id = ?",
+            subscription_id
+        )
+        .fetch_all(&self.0)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.player_name, row.missing_polls))
+            .collect())
+    }
+
+    /// Inserts or resets a player in the roster, setting their missing-poll
+    /// count (0 when present, incremented when pending a leave report).
+    pub async fn set_roster_player(
+        &self,
+        subscription_id: i64,
+        player_name: &str,
+        missing_polls: i64,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "INSERT INTO subscription_players (subscription_id, player_name, missing_polls)
+            VALUES (?, ?, ?)
+            ON CONFLICT (subscription_id, player_name) DO UPDATE SET missing_polls = ?",
+            subscription_id,
+            player_name,
+            missing_polls,
+            missing_polls,
+        )
+        .execute(&self.0)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_roster_player(
+        &self,
+        subscription_id: i64,
+        player_name: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "DELETE FROM subscription_players WHERE subscription_id = ? AND player_name = ?",
+            subscription_id,
+            player_name,
+        )
+        .execute(&self.0)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn record_player_count(
+        &self,
+        subscription_id: i64,
+        timestamp: i64,
+        player_count: u32,
+        map: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let player_count = player_count as i64;
+        sqlx::query!(
+            "INSERT INTO player_count_history (subscription_id, timestamp, player_count, map)
+            VALUES (?, ?, ?, ?)",
+            subscription_id,
+            timestamp,
+            player_count,
+            map,
+        )
+        .execute(&self.0)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the recorded history for a subscription since `since` (a unix
+    /// timestamp), ordered oldest first.
+    pub async fn get_player_count_history(
+        &self,
+        subscription_id: i64,
+        since: i64,
+    ) -> anyhow::Result<Vec<PlayerCountRecord>> {
+        let rows = sqlx::query!(
+            "SELECT timestamp, player_count, map FROM player_count_history
+            WHERE subscription_id = ? AND timestamp >= ?
+            ORDER BY timestamp ASC",
+            subscription_id,
+            since,
+        )
+        .fetch_all(&self.0)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| PlayerCountRecord {
+                timestamp: row.timestamp,
+                player_count: row.player_count as u32,
+                map: row.map,
+            })
+            .collect())
+    }
+
+    /// Deletes history rows older than `before` to keep the database bounded.
+    pub async fn prune_player_count_history(&self, before: i64) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM player_count_history WHERE timestamp < ?",
+            before
+        )
+        .execute(&self.0)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn get_guild_settings(&self, guild_id: GuildId) -> anyhow::Result<GuildSettings> {
+        let guild_id = guild_id.0 as i64;
+        let row = sqlx::query!(
+            "SELECT timezone, poll_interval_seconds FROM guild_settings WHERE guild_id = ?",
+            guild_id
+        )
+        .fetch_optional(&self.0)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(GuildSettings::default());
+        };
+
+        let timezone = match row.timezone {
+            Some(tz) => Some(
+                tz.parse()
+                    .map_err(|_| anyhow::anyhow!("Stored timezone is not a valid IANA name: {}", tz))?,
+            ),
+            None => None,
+        };
+
+        Ok(GuildSettings {
+            timezone,
+            poll_interval_seconds: row.poll_interval_seconds.map(|s| s as u64),
+        })
+    }
+
+    pub async fn set_guild_setting(
+        &self,
+        guild_id: GuildId,
+        setting: GuildSetting,
+    ) -> anyhow::Result<()> {
+        let guild_id = guild_id.0 as i64;
+        match setting {
+            GuildSetting::Timezone(tz) => {
+                let tz = tz.name();
+                sqlx::query!(
+                    "INSERT INTO guild_settings (guild_id, timezone) VALUES (?, ?)
+                    ON CONFLICT (guild_id) DO UPDATE SET timezone = ?",
+                    guild_id,
+                    tz,
+                    tz,
+                )
+                .execute(&self.0)
+                .await?;
+            }
+            GuildSetting::PollInterval(seconds) => {
+                let seconds = seconds.map(|s| s as i64);
+                sqlx::query!(
+                    "INSERT INTO guild_settings (guild_id, poll_interval_seconds) VALUES (?, ?)
+                    ON CONFLICT (guild_id) DO UPDATE SET poll_interval_seconds = ?",
+                    guild_id,
+                    seconds,
+                    seconds,
+                )
+                .execute(&self.0)
+                .await?;
+            }
         }
-        Ok(subs)
+        Ok(())
     }
 }
 
@@ -1,153 +1,565 @@
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::net::ToSocketAddrs;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
-use a2s::info::Info;
-use a2s::players::Player;
-use a2s::A2SClient;
 use anyhow::{self, Context as AnyhowContext};
-use chrono::{DateTime, Local};
+use chrono::{Local, Utc};
+use chrono_tz::Tz;
 use dotenv::dotenv;
 use serenity::async_trait;
-use serenity::framework::standard::macros::{command, group};
-use serenity::framework::standard::{Args, CommandResult};
-use serenity::framework::StandardFramework;
-use serenity::model::prelude::{GuildId, Message};
+use serenity::builder::CreateApplicationCommands;
+use serenity::model::application::command::{Command, CommandOptionType};
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::application::interaction::autocomplete::AutocompleteInteraction;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
+use serenity::model::channel::AttachmentType;
+use serenity::model::prelude::GuildId;
+use serenity::model::Permissions;
 use serenity::prelude::*;
 
 mod db;
+mod query;
 mod types;
 
 use db::BotDb;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 use tokio::time::Duration;
 
-use crate::types::Subscription;
+use crate::query::{Game, Queryer, ServerStatus};
+use crate::types::{GuildSetting, Subscription};
+
+/// Upper bound on the exponential backoff multiplier for failing servers.
+const MAX_BACKOFF_MULTIPLIER: u32 = 10;
+
+/// Maximum number of server queries running at once in the poll loop.
+const MAX_CONCURRENT_QUERIES: usize = 16;
 
 #[derive(Debug)]
 struct Config {
     discord_token: String,
     poll_interval: Duration,
+    history_retention_days: Option<u64>,
 }
 
 struct Handler {
     is_loop_running: AtomicBool,
     config: Arc<Config>,
-    a2s_client: Arc<A2SClient>,
+    queryer: Arc<Queryer>,
 }
 
 impl Handler {
     async fn new(config: Arc<Config>) -> Self {
-        let a2s_client = A2SClient::new().await.expect("Failed to create A2S client");
+        let queryer = Queryer::new().await.expect("Failed to create query backend");
 
         Self {
             is_loop_running: AtomicBool::new(false),
             config,
-            a2s_client: Arc::new(a2s_client),
+            queryer: Arc::new(queryer),
         }
     }
 }
 
-#[group]
-#[commands(follow_server, unfollow_server)]
-#[allowed_roles("gorilladmin")]
-#[owner_privilege(false)]
-struct AdminOnly;
+/// Registers the bot's application (slash) commands. Administrative commands are
+/// scoped with `default_member_permissions(ADMINISTRATOR)`, which replaces the
+/// old `gorilladmin` role group.
+fn register_application_commands(
+    commands: &mut CreateApplicationCommands,
+) -> &mut CreateApplicationCommands {
+    commands
+        .create_application_command(|c| {
+            c.name("follow")
+                .description("Follow a game server's status in this channel")
+                .default_member_permissions(Permissions::ADMINISTRATOR)
+                .dm_permission(false)
+                .create_option(|o| {
+                    o.name("hostname")
+                        .description("Server host, optionally with :port")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                        .set_autocomplete(true)
+                })
+                .create_option(|o| {
+                    o.name("game")
+                        .description("Query protocol (defaults to Source)")
+                        .kind(CommandOptionType::String)
+                        .add_string_choice("Source / A2S", "source")
+                        .add_string_choice("Minecraft", "minecraft")
+                        .add_string_choice("GameSpy Query", "gamespy")
+                })
+        })
+        .create_application_command(|c| {
+            c.name("unfollow")
+                .description("Stop following every server in this channel")
+                .default_member_permissions(Permissions::ADMINISTRATOR)
+                .dm_permission(false)
+        })
+        .create_application_command(|c| {
+            c.name("notify_players")
+                .description("Toggle join/leave notifications for this channel")
+                .default_member_permissions(Permissions::ADMINISTRATOR)
+                .dm_permission(false)
+                .create_option(|o| {
+                    o.name("enabled")
+                        .description("Whether to post join/leave messages")
+                        .kind(CommandOptionType::Boolean)
+                        .required(true)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("timezone")
+                .description("Set the guild's IANA timezone for timestamps")
+                .default_member_permissions(Permissions::ADMINISTRATOR)
+                .dm_permission(false)
+                .create_option(|o| {
+                    o.name("timezone")
+                        .description("IANA timezone name, e.g. Europe/Helsinki")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("poll_interval")
+                .description("Set the guild's poll interval in seconds")
+                .default_member_permissions(Permissions::ADMINISTRATOR)
+                .dm_permission(false)
+                .create_option(|o| {
+                    o.name("seconds")
+                        .description("Interval between polls, in seconds")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(1)
+                        .required(true)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("history")
+                .description("Export player-count history as CSV")
+                .default_member_permissions(Permissions::ADMINISTRATOR)
+                .dm_permission(false)
+                .create_option(|o| {
+                    o.name("hours")
+                        .description("Window length in hours (defaults to 7 days)")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(1)
+                })
+        })
+}
 
-#[command]
-async fn follow_server(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    log::info!(
-        "Received follow_server command in channel {}",
-        msg.channel_id
-    );
+/// Sends an ephemeral text reply to an application command.
+async fn respond_ephemeral(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    content: impl Into<String>,
+) -> anyhow::Result<()> {
+    command
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| d.ephemeral(true).content(content))
+        })
+        .await?;
+    Ok(())
+}
 
-    if args.is_empty() {
-        msg.reply(ctx, "Expected server hostname").await?;
-        return CommandResult::Ok(());
+fn option_str(command: &ApplicationCommandInteraction, name: &str) -> Option<String> {
+    command
+        .data
+        .options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+}
+
+fn option_i64(command: &ApplicationCommandInteraction, name: &str) -> Option<i64> {
+    command
+        .data
+        .options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_i64())
+}
+
+fn option_bool(command: &ApplicationCommandInteraction, name: &str) -> Option<bool> {
+    command
+        .data
+        .options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_bool())
+}
+
+async fn dispatch_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<()> {
+    match command.data.name.as_str() {
+        "follow" => follow_command(ctx, command).await,
+        "unfollow" => unfollow_command(ctx, command).await,
+        "notify_players" => notify_players_command(ctx, command).await,
+        "timezone" => timezone_command(ctx, command).await,
+        "poll_interval" => poll_interval_command(ctx, command).await,
+        "history" => history_command(ctx, command).await,
+        other => {
+            log::warn!("Received unknown command: {}", other);
+            respond_ephemeral(ctx, command, "Unknown command").await
+        }
     }
+}
 
-    let server_hostname = args.trimmed().current().unwrap();
+async fn follow_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<()> {
+    let server_hostname = option_str(command, "hostname").unwrap_or_default();
+    let game = match option_str(command, "game") {
+        Some(raw) => match raw.parse::<Game>() {
+            Ok(game) => game,
+            Err(_) => {
+                return respond_ephemeral(ctx, command, format!("Unknown game/protocol: {}", raw))
+                    .await;
+            }
+        },
+        None => Game::default(),
+    };
 
     log::info!("Parsing & resolving server hostname: {}", server_hostname);
 
     match server_hostname.to_socket_addrs() {
-        Ok(mut server_hostnames) => {
-            if server_hostnames.next().is_none() {
+        Ok(mut addrs) => {
+            if addrs.next().is_none() {
                 log::warn!("Failed to resolve server address: {}", server_hostname);
-                msg.reply(ctx, "Failed to resolve server address").await?;
-                return CommandResult::Ok(());
-            };
+                return respond_ephemeral(ctx, command, "Failed to resolve server address").await;
+            }
         }
         Err(_) => {
             log::warn!("Invalid server hostname: {}", server_hostname);
-            msg.reply(ctx, "Invalid server hostname").await?;
-            return CommandResult::Ok(());
+            return respond_ephemeral(ctx, command, "Invalid server hostname").await;
         }
-    };
+    }
 
-    let message = msg
+    let message = command
         .channel_id
-        .send_message(&ctx, |m| {
-            m.embed(get_server_status_setter(None, server_hostname))
+        .send_message(ctx, |m| {
+            m.embed(get_server_status_setter(None, &server_hostname, None))
         })
         .await?;
 
-    let data = ctx.data.read().await;
-    let db = data.get::<BotDb>().unwrap().clone();
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<BotDb>().unwrap().clone()
+    };
 
     db.upsert_subscription(Subscription {
         id: None,
-        guild_id: msg.guild_id.unwrap(),
-        channel_id: msg.channel_id,
+        guild_id: command.guild_id.unwrap(),
+        channel_id: command.channel_id,
         message_id: message.id,
-        server_hostname: server_hostname.to_string(),
+        server_hostname: server_hostname.clone(),
+        game,
+        notify_players: false,
+        consecutive_failures: 0,
+        last_status: true,
+        last_success_at: None,
     })
     .await?;
 
-    msg.react(ctx, '👍').await?;
+    respond_ephemeral(ctx, command, format!("Now following {} 👍", server_hostname)).await
+}
+
+async fn unfollow_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<()> {
+    // Present a Yes/No confirmation to guard against accidental mass-unsubscription.
+    command
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| {
+                    d.ephemeral(true)
+                        .content("Unfollow **all** servers in this channel?")
+                        .components(|c| {
+                            c.create_action_row(|row| {
+                                row.create_button(|b| {
+                                    b.custom_id("unfollow_confirm")
+                                        .label("Yes, unfollow")
+                                        .style(ButtonStyle::Danger)
+                                })
+                                .create_button(|b| {
+                                    b.custom_id("unfollow_cancel")
+                                        .label("Cancel")
+                                        .style(ButtonStyle::Secondary)
+                                })
+                            })
+                        })
+                })
+        })
+        .await?;
+
+    let prompt = command.get_interaction_response(ctx).await?;
+    let interaction = prompt
+        .await_component_interaction(&ctx.shard)
+        .timeout(Duration::from_secs(30))
+        .await;
 
-    CommandResult::Ok(())
+    match interaction {
+        Some(component) if component.data.custom_id == "unfollow_confirm" => {
+            let db = {
+                let data = ctx.data.read().await;
+                data.get::<BotDb>().unwrap().clone()
+            };
+            db.delete_subscriptions_by_channel_id(command.channel_id)
+                .await?;
+
+            update_component(ctx, &component, "Unsubscribed from server status updates :(").await
+        }
+        Some(component) => {
+            update_component(ctx, &component, "Cancelled, no changes made").await
+        }
+        None => {
+            command
+                .edit_original_interaction_response(ctx, |r| {
+                    r.content("Confirmation timed out, no changes made")
+                        .components(|c| c)
+                })
+                .await?;
+            Ok(())
+        }
+    }
 }
 
-#[command]
-async fn unfollow_server(ctx: &Context, msg: &Message) -> CommandResult {
-    let data = ctx.data.read().await;
-    let db = data.get::<BotDb>().unwrap();
+/// Replaces the confirmation prompt with a result message, clearing the buttons.
+async fn update_component(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    content: &str,
+) -> anyhow::Result<()> {
+    component
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| d.content(content).components(|c| c))
+        })
+        .await?;
+    Ok(())
+}
 
-    db.delete_subscriptions_by_channel_id(msg.channel_id)
+async fn notify_players_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<()> {
+    let enabled = option_bool(command, "enabled").unwrap_or(false);
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<BotDb>().unwrap().clone()
+    };
+    let changed = db
+        .set_notify_players_by_channel(command.channel_id, enabled)
         .await?;
 
-    msg.reply(ctx, "Unsubscribed from server status updates :(")
+    if changed == 0 {
+        respond_ephemeral(ctx, command, "No server subscriptions in this channel").await
+    } else {
+        respond_ephemeral(
+            ctx,
+            command,
+            format!(
+                "Join/leave notifications {} for this channel",
+                if enabled { "enabled" } else { "disabled" }
+            ),
+        )
+        .await
+    }
+}
+
+async fn timezone_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<()> {
+    let raw = option_str(command, "timezone").unwrap_or_default();
+    let timezone = match raw.parse::<Tz>() {
+        Ok(tz) => tz,
+        Err(_) => {
+            return respond_ephemeral(ctx, command, format!("Unknown timezone: {}", raw)).await;
+        }
+    };
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<BotDb>().unwrap().clone()
+    };
+    db.set_guild_setting(command.guild_id.unwrap(), GuildSetting::Timezone(timezone))
         .await?;
 
-    CommandResult::Ok(())
+    respond_ephemeral(ctx, command, format!("Timezone set to {}", timezone)).await
+}
+
+async fn poll_interval_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<()> {
+    let seconds = option_i64(command, "seconds").unwrap_or(0).max(1) as u64;
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<BotDb>().unwrap().clone()
+    };
+    db.set_guild_setting(
+        command.guild_id.unwrap(),
+        GuildSetting::PollInterval(Some(seconds)),
+    )
+    .await?;
+
+    respond_ephemeral(ctx, command, format!("Poll interval set to {} seconds", seconds)).await
+}
+
+async fn history_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<()> {
+    let hours = option_i64(command, "hours").unwrap_or(24 * 7).max(1);
+    let since = Utc::now().timestamp() - hours * 3600;
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<BotDb>().unwrap().clone()
+    };
+
+    let subscriptions = db
+        .get_subscriptions()
+        .await?
+        .into_iter()
+        .filter(|sub| sub.channel_id == command.channel_id)
+        .collect::<Vec<_>>();
+
+    if subscriptions.is_empty() {
+        return respond_ephemeral(ctx, command, "No server subscriptions in this channel").await;
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["server", "timestamp", "player_count", "map"])?;
+
+    let mut rows = 0usize;
+    for subscription in &subscriptions {
+        let id = subscription.id.unwrap();
+        for record in db.get_player_count_history(id, since).await? {
+            writer.write_record([
+                subscription.server_hostname.as_str(),
+                &record.timestamp.to_string(),
+                &record.player_count.to_string(),
+                record.map.as_deref().unwrap_or(""),
+            ])?;
+            rows += 1;
+        }
+    }
+
+    if rows == 0 {
+        return respond_ephemeral(ctx, command, "No history recorded for the requested window")
+            .await;
+    }
+
+    let bytes = writer.into_inner()?;
+    let attachment = AttachmentType::Bytes {
+        data: bytes.into(),
+        filename: "history.csv".to_string(),
+    };
+
+    command
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| {
+                    d.content(format!("Player count history for the last {}h", hours))
+                        .add_file(attachment)
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Suggests hostnames the guild already follows for the `follow` command's
+/// autocomplete.
+async fn handle_autocomplete(
+    ctx: &Context,
+    autocomplete: &AutocompleteInteraction,
+) -> anyhow::Result<()> {
+    let focused = autocomplete
+        .data
+        .options
+        .iter()
+        .find(|o| o.focused)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<BotDb>().unwrap().clone()
+    };
+
+    let mut hostnames = db
+        .get_subscriptions()
+        .await?
+        .into_iter()
+        .filter(|sub| Some(sub.guild_id) == autocomplete.guild_id)
+        .map(|sub| sub.server_hostname)
+        .filter(|host| host.to_ascii_lowercase().contains(&focused))
+        .collect::<Vec<_>>();
+    hostnames.sort();
+    hostnames.dedup();
+    hostnames.truncate(25); // Discord allows at most 25 autocomplete choices.
+
+    autocomplete
+        .create_autocomplete_response(ctx, |r| {
+            for host in hostnames {
+                r.add_string_choice(&host, &host);
+            }
+            r
+        })
+        .await?;
+
+    Ok(())
 }
 
 fn get_server_status_setter(
-    info: Option<(Info, Vec<Player>)>,
+    status: Option<ServerStatus>,
     address: &str,
+    timezone: Option<Tz>,
 ) -> impl FnOnce(&mut serenity::builder::CreateEmbed) -> &mut serenity::builder::CreateEmbed + '_ {
-    let now: DateTime<Local> = Local::now();
-    let updated_at = now.format("%Y-%m-%d %H:%M:%S").to_string();
+    // Render the timestamp in the guild's configured timezone, falling back to
+    // the host machine's local time when the guild has not set one.
+    let updated_at = match timezone {
+        Some(tz) => Utc::now()
+            .with_timezone(&tz)
+            .format("%Y-%m-%d %H:%M:%S %Z")
+            .to_string(),
+        None => Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    };
 
-    move |embed| match info {
-        Some((info, players)) => {
+    move |embed| match status {
+        Some(status) => {
             let embed = embed
-                .field("Server name", info.name.clone(), false)
+                .field("Server name", status.name.clone(), false)
                 .field("Server address", address, false)
-                .field("Map", info.map.clone(), false)
-                .field("Player count", info.players, false);
+                .field("Map", status.map.clone().unwrap_or_else(|| "-".to_string()), false)
+                .field(
+                    "Player count",
+                    format!("{} / {}", status.players, status.max_players),
+                    false,
+                );
 
-            let embed = if players.is_empty() {
+            let embed = if status.player_names.is_empty() {
                 embed
             } else {
-                let players = players
-                    .into_iter()
-                    .map(|player| player.name)
-                    .collect::<Vec<_>>()
-                    .join(", ");
-
-                embed.field("Players", players, false)
+                embed.field("Players", status.player_names.join(", "), false)
             };
 
             embed.field("Updated at", updated_at, false)
@@ -171,63 +583,233 @@ fn is_message_was_removed_error(err: &SerenityError) -> bool {
     }
 }
 
+/// Formats a duration in whole seconds as a compact `1h 2m 3s` string,
+/// omitting zero hour and minute components (seconds are always shown).
+fn format_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    let (hours, minutes, secs) = (seconds / 3600, (seconds % 3600) / 60, seconds % 60);
+    let mut parts = Vec::new();
+    if hours > 0 {
+        parts.push(format!("{}h", hours));
+    }
+    if minutes > 0 {
+        parts.push(format!("{}m", minutes));
+    }
+    parts.push(format!("{}s", secs));
+    parts.join(" ")
+}
+
+/// The roster changes implied by comparing a fresh player list against the
+/// persisted roster: who to announce, and how to update the stored rows.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct RosterDiff {
+    /// Players to announce as newly joined.
+    joined: Vec<String>,
+    /// Players to announce as left (absent for two consecutive polls).
+    left: Vec<String>,
+    /// Roster rows to upsert as `(name, missing_polls)`.
+    upserts: Vec<(String, i64)>,
+    /// Players to delete from the roster once their leave has been reported.
+    removed: Vec<String>,
+}
+
+/// Computes the [`RosterDiff`] for a poll. A player must be absent for two
+/// consecutive polls before a leave is reported, which debounces servers that
+/// drop players from the query for a single cycle. Present players are only
+/// upserted when their stored row needs changing (new, or a pending-leave
+/// counter to reset), so a stable roster does not incur a write per player
+/// every cycle.
+fn plan_roster_diff(stored: &[(String, i64)], current: &[String]) -> RosterDiff {
+    let stored_polls: HashMap<&str, i64> =
+        stored.iter().map(|(name, polls)| (name.as_str(), *polls)).collect();
+    let current_names: HashSet<&str> = current.iter().map(|name| name.as_str()).collect();
+
+    let mut diff = RosterDiff::default();
+
+    for name in current {
+        match stored_polls.get(name.as_str()) {
+            None => {
+                diff.joined.push(name.clone());
+                diff.upserts.push((name.clone(), 0));
+            }
+            Some(&missing_polls) if missing_polls != 0 => {
+                diff.upserts.push((name.clone(), 0));
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (name, missing_polls) in stored {
+        if current_names.contains(name.as_str()) {
+            continue;
+        }
+
+        let missing_polls = missing_polls + 1;
+        if missing_polls >= 2 {
+            diff.left.push(name.clone());
+            diff.removed.push(name.clone());
+        } else {
+            diff.upserts.push((name.clone(), missing_polls));
+        }
+    }
+
+    diff
+}
+
+/// Compares the freshly-fetched player list against the persisted roster and
+/// posts a short notification for each player who joined or left, and applies
+/// the resulting roster updates.
+async fn diff_and_notify_roster(
+    ctx: &Context,
+    db: &BotDb,
+    subscription: &Subscription,
+    current: &[String],
+) -> anyhow::Result<()> {
+    let id = subscription
+        .id
+        .expect("Subscription from database should always have an ID");
+
+    let stored = db.get_roster(id).await?;
+    let diff = plan_roster_diff(&stored, current);
+
+    for name in &diff.joined {
+        subscription
+            .channel_id
+            .say(ctx, format!("🟢 {} joined", name))
+            .await?;
+    }
+    for (name, missing_polls) in &diff.upserts {
+        db.set_roster_player(id, name, *missing_polls).await?;
+    }
+    for name in &diff.left {
+        subscription
+            .channel_id
+            .say(ctx, format!("🔴 {} left", name))
+            .await?;
+    }
+    for name in &diff.removed {
+        db.remove_roster_player(id, name).await?;
+    }
+
+    Ok(())
+}
+
 async fn handle_subscription(
     ctx: &Context,
     db: &BotDb,
-    a2s_client: &A2SClient,
+    queryer: &Queryer,
     subscription: Subscription,
 ) -> anyhow::Result<()> {
-    let info = a2s_client.info(subscription.server_hostname.as_str()).await;
+    let status = queryer
+        .query(subscription.game, subscription.server_hostname.as_str())
+        .await;
 
-    let info = match info {
-        Ok(info) => {
+    let status = match status {
+        Ok(status) => {
             log::info!(
-                "Got server info for {}: {:?}",
+                "Got server status for {}: {:?}",
                 subscription.server_hostname,
-                info
+                status
             );
+            Some(status)
+        }
 
-            // If there are any players, get them too
-            if info.players > 0 {
-                let players = a2s_client
-                    .players(subscription.server_hostname.as_str())
-                    .await;
+        Err(err) => {
+            log::warn!(
+                "Failed to get server status for {}: {:?}",
+                subscription.server_hostname,
+                err
+            );
 
-                match players {
-                    Err(err) => {
-                        log::warn!(
-                            "Failed to get server players for {}: {:?}",
-                            subscription.server_hostname,
-                            err
-                        );
-                        Some((info, Vec::new()))
-                    }
-                    Ok(players) => {
-                        log::info!(
-                            "Got server players for {}: {:?}",
-                            subscription.server_hostname,
-                            players
-                        );
-                        Some((info, players))
-                    }
-                }
-            } else {
-                Some((info, Vec::new()))
+            None
+        }
+    };
+
+    // Outage tracking: detect up<->down transitions, alert on them, and
+    // persist the failure counter so the poll loop can back off dead hosts.
+    let id = subscription
+        .id
+        .expect("Subscription from database should always have an ID");
+    let now = Utc::now().timestamp();
+    if status.is_some() {
+        if !subscription.last_status {
+            let downtime = subscription
+                .last_success_at
+                .map(|since| format_duration(now - since))
+                .unwrap_or_else(|| "an unknown amount of time".to_string());
+            if let Err(err) = subscription
+                .channel_id
+                .say(ctx, format!("✅ Server is back online after {}", downtime))
+                .await
+            {
+                log::error!("Failed to post recovery message: {:?}", err);
+            }
+        }
+        db.update_outage_state(id, true, 0, Some(now)).await?;
+    } else {
+        if subscription.last_status {
+            if let Err(err) = subscription
+                .channel_id
+                .say(ctx, "⚠️ Server is offline")
+                .await
+            {
+                log::error!("Failed to post offline message: {:?}", err);
             }
         }
+        db.update_outage_state(
+            id,
+            false,
+            subscription.consecutive_failures + 1,
+            subscription.last_success_at,
+        )
+        .await?;
+    }
 
-        Err(err) => {
+    // Record the population for historical analytics on every successful poll.
+    if let Some(status) = &status {
+        if let Err(err) = db
+            .record_player_count(id, now, status.players, status.map.as_deref())
+            .await
+        {
             log::warn!(
-                "Failed to get server info for {}: {:?}",
+                "Failed to record player count for {}: {:?}",
                 subscription.server_hostname,
                 err
             );
+        }
+    }
+
+    // Diff the roster and post join/leave notifications. A failed or empty
+    // query is treated as "no change" so we never emit a mass-leave burst.
+    if subscription.notify_players {
+        if let Some(status) = &status {
+            if !status.player_names.is_empty() {
+                if let Err(err) = diff_and_notify_roster(ctx, db, &subscription, &status.player_names).await
+                {
+                    log::warn!(
+                        "Failed to diff roster for {}: {:?}",
+                        subscription.server_hostname,
+                        err
+                    );
+                }
+            }
+        }
+    }
 
+    let timezone = match db.get_guild_settings(subscription.guild_id).await {
+        Ok(settings) => settings.timezone,
+        Err(err) => {
+            log::warn!(
+                "Failed to load guild settings for {}: {:?}",
+                subscription.guild_id,
+                err
+            );
             None
         }
     };
 
-    let status_setter = get_server_status_setter(info, subscription.server_hostname.as_ref());
+    let status_setter =
+        get_server_status_setter(status, subscription.server_hostname.as_ref(), timezone);
 
     let update_message_result = subscription
         .channel_id
@@ -257,9 +839,35 @@ async fn handle_subscription(
 
 #[async_trait]
 impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: serenity::model::gateway::Ready) {
+        log::info!("Connected as {}, registering application commands", ready.user.name);
+
+        if let Err(err) =
+            Command::set_global_application_commands(&ctx, register_application_commands).await
+        {
+            log::error!("Failed to register application commands: {:?}", err);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let result = match &interaction {
+            Interaction::ApplicationCommand(command) => dispatch_command(&ctx, command).await,
+            Interaction::Autocomplete(autocomplete) => {
+                handle_autocomplete(&ctx, autocomplete).await
+            }
+            // Component interactions are collected inline by the command that
+            // created them, so nothing to do here.
+            _ => Ok(()),
+        };
+
+        if let Err(err) = result {
+            log::error!("Failed to handle interaction: {:?}", err);
+        }
+    }
+
     async fn cache_ready(&self, ctx: Context, _guilds: Vec<GuildId>) {
         let ctx: Context = ctx.clone();
-        let a2s_client = self.a2s_client.clone();
+        let queryer = self.queryer.clone();
         let config = self.config.clone();
 
         let db = {
@@ -267,18 +875,98 @@ impl EventHandler for Handler {
             data.get::<BotDb>().cloned().unwrap()
         };
 
-        if !self.is_loop_running.load(Ordering::Relaxed) {
+        // `cache_ready` fires on every gateway (re)connect and resume, so guard
+        // against spawning a second poll loop on reconnect — otherwise each one
+        // would post duplicate embeds and double up join/leave and outage alerts.
+        if !self.is_loop_running.swap(true, Ordering::SeqCst) {
             tokio::spawn(async move {
+                // Cap the number of in-flight queries so a large fleet can't
+                // exhaust sockets or file descriptors.
+                let query_semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_QUERIES));
+                let mut last_polled: HashMap<i64, Instant> = HashMap::new();
+
                 loop {
-                    let subscriptions = db.get_subscriptions().await.unwrap();
+                    // Prune history beyond the retention window to keep the
+                    // SQLite file bounded.
+                    if let Some(days) = config.history_retention_days {
+                        let cutoff = Utc::now().timestamp() - (days as i64) * 86_400;
+                        if let Err(err) = db.prune_player_count_history(cutoff).await {
+                            log::warn!("Failed to prune player count history: {:?}", err);
+                        }
+                    }
+
+                    // Log-and-continue instead of unwrapping: a transient query
+                    // error must not tear down the whole updater.
+                    let subscriptions = match db.get_subscriptions().await {
+                        Ok(subscriptions) => subscriptions,
+                        Err(err) => {
+                            log::error!("Failed to load subscriptions: {:?}", err);
+                            tokio::time::sleep(config.poll_interval).await;
+                            continue;
+                        }
+                    };
+
+                    let mut tasks = JoinSet::new();
+                    // Wake up as often as the shortest effective interval so a
+                    // guild that configures a poll interval below the global
+                    // default isn't silently floored at the global cadence.
+                    let mut next_sleep = config.poll_interval;
 
                     for subscription in subscriptions {
-                        handle_subscription(&ctx, &db, &a2s_client, subscription)
-                            .await
-                            .unwrap();
+                        // Honour the guild's configured poll interval, falling
+                        // back to the global default.
+                        let interval = match db.get_guild_settings(subscription.guild_id).await {
+                            Ok(settings) => settings
+                                .poll_interval_seconds
+                                .map(Duration::from_secs)
+                                .unwrap_or(config.poll_interval),
+                            Err(_) => config.poll_interval,
+                        };
+
+                        // Exponentially back off servers that keep failing so
+                        // we stop wasting query timeouts on long-dead hosts.
+                        let multiplier = 2u32
+                            .saturating_pow(subscription.consecutive_failures)
+                            .min(MAX_BACKOFF_MULTIPLIER);
+                        let interval = interval * multiplier;
+                        next_sleep = next_sleep.min(interval);
+
+                        let id = subscription.id.unwrap();
+                        let due = last_polled
+                            .get(&id)
+                            .map_or(true, |last| last.elapsed() >= interval);
+                        if !due {
+                            continue;
+                        }
+                        last_polled.insert(id, Instant::now());
+
+                        let ctx = ctx.clone();
+                        let db = db.clone();
+                        let queryer = queryer.clone();
+                        let query_semaphore = query_semaphore.clone();
+                        let hostname = subscription.server_hostname.clone();
+
+                        tasks.spawn(async move {
+                            let _permit = query_semaphore.acquire().await;
+                            // Capture errors per subscription so one bad server
+                            // only drops its own update for this cycle.
+                            if let Err(err) =
+                                handle_subscription(&ctx, &db, &queryer, subscription).await
+                            {
+                                log::error!("Failed to update subscription {}: {:?}", hostname, err);
+                            }
+                        });
                     }
 
-                    tokio::time::sleep(config.poll_interval).await;
+                    // Drain the set so panics are caught and logged rather than
+                    // silently aborting the task.
+                    while let Some(result) = tasks.join_next().await {
+                        if let Err(err) = result {
+                            log::error!("A subscription update task panicked: {:?}", err);
+                        }
+                    }
+
+                    tokio::time::sleep(next_sleep).await;
                 }
             });
         }
@@ -296,9 +984,19 @@ fn get_config_from_env() -> anyhow::Result<Config> {
     let token =
         env::var("GORILLA_DISCORD_TOKEN").context("Expected GORILLA_DISCORD_TOKEN env var")?;
 
+    let history_retention_days = match env::var("GORILLA_HISTORY_RETENTION_DAYS") {
+        Ok(value) => Some(
+            value
+                .parse::<u64>()
+                .context("Failed to parse GORILLA_HISTORY_RETENTION_DAYS env var")?,
+        ),
+        Err(_) => None,
+    };
+
     Ok(Config {
         poll_interval: Duration::from_secs(poll_interval),
         discord_token: token,
+        history_retention_days,
     })
 }
 
@@ -309,7 +1007,7 @@ async fn main() -> anyhow::Result<()> {
 
     log::info!("Loading gorillabot.db");
 
-    let db = BotDb::new("gorillabot.db");
+    let db = BotDb::new("gorillabot.db").await?;
 
     log::info!("Migrating database");
 
@@ -318,14 +1016,10 @@ async fn main() -> anyhow::Result<()> {
     log::info!("Creating Discord client");
 
     let config = get_config_from_env()?;
-    let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
-    let framework = StandardFramework::new()
-        .configure(|c| c.prefix("!"))
-        .group(&ADMINONLY_GROUP);
+    let intents = GatewayIntents::non_privileged();
 
     let mut client = Client::builder(config.discord_token.clone(), intents)
         .event_handler(Handler::new(Arc::new(config)).await)
-        .framework(framework)
         .await?;
 
     client.data.write().await.insert::<BotDb>(db);
@@ -336,3 +1030,94 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod roster_tests {
+    use super::{plan_roster_diff, RosterDiff};
+
+    fn names(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn new_player_is_announced_and_upserted() {
+        let diff = plan_roster_diff(&[], &names(&["alice"]));
+        assert_eq!(
+            diff,
+            RosterDiff {
+                joined: names(&["alice"]),
+                upserts: vec![("alice".to_string(), 0)],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn stable_present_player_triggers_no_write() {
+        let stored = vec![("alice".to_string(), 0)];
+        let diff = plan_roster_diff(&stored, &names(&["alice"]));
+        assert_eq!(diff, RosterDiff::default());
+    }
+
+    #[test]
+    fn leaving_is_debounced_over_two_polls() {
+        // First miss: no announcement, just a bumped pending-leave counter.
+        let stored = vec![("alice".to_string(), 0)];
+        let first = plan_roster_diff(&stored, &[]);
+        assert_eq!(
+            first,
+            RosterDiff {
+                upserts: vec![("alice".to_string(), 1)],
+                ..Default::default()
+            }
+        );
+
+        // Second consecutive miss: announce the leave and drop the row.
+        let stored = vec![("alice".to_string(), 1)];
+        let second = plan_roster_diff(&stored, &[]);
+        assert_eq!(
+            second,
+            RosterDiff {
+                left: names(&["alice"]),
+                removed: names(&["alice"]),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn reappearing_player_resets_pending_leave() {
+        let stored = vec![("alice".to_string(), 1)];
+        let diff = plan_roster_diff(&stored, &names(&["alice"]));
+        assert_eq!(
+            diff,
+            RosterDiff {
+                upserts: vec![("alice".to_string(), 0)],
+                ..Default::default()
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod duration_tests {
+    use super::format_duration;
+
+    #[test]
+    fn formats_seconds_only() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(45), "45s");
+    }
+
+    #[test]
+    fn omits_leading_zero_components() {
+        assert_eq!(format_duration(90), "1m 30s");
+        assert_eq!(format_duration(3600), "1h 0s");
+        assert_eq!(format_duration(3661), "1h 1m 1s");
+    }
+
+    #[test]
+    fn clamps_negative_durations_to_zero() {
+        assert_eq!(format_duration(-5), "0s");
+    }
+}
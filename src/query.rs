@@ -0,0 +1,445 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use a2s::A2SClient;
+use anyhow::{anyhow, bail, Context};
+use serenity::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+/// A normalized snapshot of a game server's state, independent of the
+/// wire protocol it was obtained with.
+#[derive(Debug, Clone)]
+pub struct ServerStatus {
+    pub name: String,
+    pub map: Option<String>,
+    pub players: u32,
+    pub max_players: u32,
+    pub player_names: Vec<String>,
+}
+
+/// A backend capable of querying a single game server over some protocol.
+#[async_trait]
+pub trait ServerQuery: Send + Sync {
+    async fn query(&self, addr: &str) -> anyhow::Result<ServerStatus>;
+}
+
+/// The query protocol used to talk to a subscribed server. Stored per
+/// subscription and used to pick a [`ServerQuery`] backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Game {
+    /// Valve's Source (A2S) query protocol.
+    Source,
+    /// Minecraft's Server List Ping (the handshake the client uses for the
+    /// multiplayer list).
+    Minecraft,
+    /// The GameSpy-style UDP query protocol (challenge + full stat).
+    GameSpy,
+}
+
+impl Game {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Game::Source => "source",
+            Game::Minecraft => "minecraft",
+            Game::GameSpy => "gamespy",
+        }
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::Source
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Game {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "source" | "a2s" | "steam" => Ok(Game::Source),
+            "minecraft" | "mc" => Ok(Game::Minecraft),
+            "gamespy" | "query" => Ok(Game::GameSpy),
+            other => bail!("Unknown game/protocol: {}", other),
+        }
+    }
+}
+
+/// Holds one backend per supported protocol and dispatches queries based on
+/// the subscription's [`Game`].
+pub struct Queryer {
+    a2s_client: A2SClient,
+}
+
+impl Queryer {
+    pub async fn new() -> anyhow::Result<Self> {
+        let a2s_client = A2SClient::new()
+            .await
+            .context("Failed to create A2S client")?;
+        Ok(Self { a2s_client })
+    }
+
+    pub async fn query(&self, game: Game, addr: &str) -> anyhow::Result<ServerStatus> {
+        match game {
+            Game::Source => SourceQuery(&self.a2s_client).query(addr).await,
+            Game::Minecraft => MinecraftQuery.query(addr).await,
+            Game::GameSpy => GameSpyQuery.query(addr).await,
+        }
+    }
+}
+
+/// A2S / Source backend, wrapping the shared [`A2SClient`].
+struct SourceQuery<'a>(&'a A2SClient);
+
+#[async_trait]
+impl<'a> ServerQuery for SourceQuery<'a> {
+    async fn query(&self, addr: &str) -> anyhow::Result<ServerStatus> {
+        let info = self.0.info(addr).await.context("A2S info query failed")?;
+
+        // Only pull the player list if the info query claims anyone is online.
+        let player_names = if info.players > 0 {
+            match self.0.players(addr).await {
+                Ok(players) => players.into_iter().map(|p| p.name).collect(),
+                Err(err) => {
+                    log::warn!("Failed to get A2S players for {}: {:?}", addr, err);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        Ok(ServerStatus {
+            name: info.name,
+            map: Some(info.map),
+            players: info.players as u32,
+            max_players: info.max_players as u32,
+            player_names,
+        })
+    }
+}
+
+/// Minecraft Server List Ping (1.7+ JSON handshake over TCP).
+struct MinecraftQuery;
+
+#[async_trait]
+impl ServerQuery for MinecraftQuery {
+    async fn query(&self, addr: &str) -> anyhow::Result<ServerStatus> {
+        let (host, port) = split_host_port(addr, 25565)?;
+
+        let mut stream = TcpStream::connect((host.as_str(), port))
+            .await
+            .context("Failed to connect to Minecraft server")?;
+
+        // Handshake packet (id 0x00) followed by an empty status request (0x00).
+        let mut handshake = Vec::new();
+        write_varint(&mut handshake, 0x00); // packet id
+        write_varint(&mut handshake, -1i32 as u32); // protocol version (-1 = status)
+        write_string(&mut handshake, &host);
+        handshake.extend_from_slice(&port.to_be_bytes());
+        write_varint(&mut handshake, 1); // next state: status
+        write_framed(&mut stream, &handshake).await?;
+        write_framed(&mut stream, &[0x00]).await?;
+
+        let response = read_framed(&mut stream).await?;
+        let mut cursor = &response[..];
+        let packet_id = read_varint(&mut cursor)?;
+        if packet_id != 0x00 {
+            bail!("Unexpected Minecraft status packet id: {}", packet_id);
+        }
+        let json = read_string(&mut cursor)?;
+        let value: serde_json::Value =
+            serde_json::from_str(&json).context("Failed to parse Minecraft status JSON")?;
+
+        let name = value
+            .get("description")
+            .map(describe_motd)
+            .unwrap_or_else(|| "Minecraft Server".to_string());
+        let players = value
+            .pointer("/players/online")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as u32;
+        let max_players = value
+            .pointer("/players/max")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as u32;
+        let player_names = value
+            .pointer("/players/sample")
+            .and_then(|v| v.as_array())
+            .map(|sample| {
+                sample
+                    .iter()
+                    .filter_map(|p| p.get("name").and_then(|n| n.as_str()))
+                    .map(|n| n.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(ServerStatus {
+            name,
+            map: None,
+            players,
+            max_players,
+            player_names,
+        })
+    }
+}
+
+/// GameSpy-style UDP query (challenge/response + full stat), as used by the
+/// UT3 engine and Minecraft's "Query" listener.
+struct GameSpyQuery;
+
+#[async_trait]
+impl ServerQuery for GameSpyQuery {
+    async fn query(&self, addr: &str) -> anyhow::Result<ServerStatus> {
+        let (host, port) = split_host_port(addr, 25565)?;
+
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .context("Failed to bind UDP socket")?;
+        socket
+            .connect((host.as_str(), port))
+            .await
+            .context("Failed to connect to GameSpy server")?;
+
+        // Handshake: magic (0xFE 0xFD), type 9 (handshake), session id.
+        let session: i32 = 1;
+        let mut handshake = vec![0xFE, 0xFD, 0x09];
+        handshake.extend_from_slice(&session.to_be_bytes());
+        socket.send(&handshake).await?;
+
+        let mut buf = [0u8; 2048];
+        let len = recv_timeout(&socket, &mut buf).await?;
+        // Response: type byte, session id (4 bytes), null-terminated token string.
+        let token_bytes = buf
+            .get(5..len)
+            .ok_or_else(|| anyhow!("GameSpy challenge response too short"))?;
+        let token: i32 = std::str::from_utf8(token_bytes)
+            .ok()
+            .and_then(|s| s.trim_end_matches('\0').parse().ok())
+            .ok_or_else(|| anyhow!("Invalid GameSpy challenge token"))?;
+
+        // Full stat request: type 0, session, challenge token, padding for full kind.
+        let mut request = vec![0xFE, 0xFD, 0x00];
+        request.extend_from_slice(&session.to_be_bytes());
+        request.extend_from_slice(&token.to_be_bytes());
+        request.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+        socket.send(&request).await?;
+
+        let len = recv_timeout(&socket, &mut buf).await?;
+        parse_gamespy_full(&buf[..len])
+    }
+}
+
+fn parse_gamespy_full(data: &[u8]) -> anyhow::Result<ServerStatus> {
+    // Skip the 5-byte header and the 11-byte "splitnum" padding.
+    let body = data
+        .get(16..)
+        .ok_or_else(|| anyhow!("GameSpy response too short"))?;
+    let mut parts = body.split(|&b| b == 0);
+
+    let mut kv = std::collections::HashMap::new();
+    loop {
+        let key = match parts.next() {
+            Some(k) if !k.is_empty() => k,
+            _ => break, // empty key terminates the key/value section
+        };
+        let value = parts.next().unwrap_or(&[]);
+        kv.insert(
+            String::from_utf8_lossy(key).into_owned(),
+            String::from_utf8_lossy(value).into_owned(),
+        );
+    }
+
+    // The player section follows a "player_" marker and a padding byte.
+    let player_names = body
+        .windows(8)
+        .position(|w| w == b"player_\0")
+        .map(|idx| {
+            body[idx + 9..]
+                .split(|&b| b == 0)
+                .map(|n| String::from_utf8_lossy(n).into_owned())
+                .take_while(|n| !n.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    Ok(ServerStatus {
+        name: kv.get("hostname").cloned().unwrap_or_default(),
+        map: kv.get("map").cloned(),
+        players: kv
+            .get("numplayers")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0),
+        max_players: kv
+            .get("maxplayers")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0),
+        player_names,
+    })
+}
+
+async fn recv_timeout(socket: &UdpSocket, buf: &mut [u8]) -> anyhow::Result<usize> {
+    tokio::time::timeout(Duration::from_secs(5), socket.recv(buf))
+        .await
+        .context("Timed out waiting for server response")?
+        .context("Failed to receive server response")
+}
+
+/// Splits an `addr` of the form `host` or `host:port`, defaulting the port.
+fn split_host_port(addr: &str, default_port: u16) -> anyhow::Result<(String, u16)> {
+    match addr.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port
+                .parse()
+                .with_context(|| format!("Invalid port in address: {}", addr))?;
+            Ok((host.to_string(), port))
+        }
+        None => Ok((addr.to_string(), default_port)),
+    }
+}
+
+fn describe_motd(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Object(obj) => {
+            let mut out = obj
+                .get("text")
+                .and_then(|t| t.as_str())
+                .unwrap_or("")
+                .to_string();
+            if let Some(extra) = obj.get("extra").and_then(|e| e.as_array()) {
+                for part in extra {
+                    out.push_str(&describe_motd(part));
+                }
+            }
+            out
+        }
+        _ => String::new(),
+    }
+}
+
+// --- Minecraft protocol primitives ------------------------------------------
+
+fn write_varint(buf: &mut Vec<u8>, value: u32) {
+    let mut value = value;
+    loop {
+        let mut byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as u32);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+async fn write_framed(stream: &mut TcpStream, payload: &[u8]) -> anyhow::Result<()> {
+    let mut framed = Vec::with_capacity(payload.len() + 5);
+    write_varint(&mut framed, payload.len() as u32);
+    framed.extend_from_slice(payload);
+    stream.write_all(&framed).await?;
+    Ok(())
+}
+
+async fn read_framed(stream: &mut TcpStream) -> anyhow::Result<Vec<u8>> {
+    let len = read_varint_async(stream).await? as usize;
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+fn read_varint(cursor: &mut &[u8]) -> anyhow::Result<i32> {
+    let mut result = 0i32;
+    for shift in (0..32).step_by(7) {
+        let (&byte, rest) = cursor
+            .split_first()
+            .ok_or_else(|| anyhow!("Unexpected end of packet while reading VarInt"))?;
+        *cursor = rest;
+        result |= ((byte & 0x7F) as i32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    bail!("VarInt is too long")
+}
+
+async fn read_varint_async(stream: &mut TcpStream) -> anyhow::Result<i32> {
+    let mut result = 0i32;
+    for shift in (0..32).step_by(7) {
+        let byte = stream.read_u8().await?;
+        result |= ((byte & 0x7F) as i32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    bail!("VarInt is too long")
+}
+
+fn read_string(cursor: &mut &[u8]) -> anyhow::Result<String> {
+    let len = read_varint(cursor)? as usize;
+    if cursor.len() < len {
+        bail!("Unexpected end of packet while reading string");
+    }
+    let (bytes, rest) = cursor.split_at(len);
+    *cursor = rest;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_varint, split_host_port, write_varint};
+
+    #[test]
+    fn varint_round_trips() {
+        for value in [0u32, 1, 127, 128, 255, 300, 25565, u32::MAX] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value);
+            let mut cursor = &buf[..];
+            let decoded = read_varint(&mut cursor).unwrap() as u32;
+            assert_eq!(decoded, value, "round-trip failed for {}", value);
+            assert!(cursor.is_empty(), "decoder left trailing bytes for {}", value);
+        }
+    }
+
+    #[test]
+    fn read_varint_rejects_truncated_input() {
+        // A byte with the continuation bit set but no following byte.
+        let mut cursor = &[0x80u8][..];
+        assert!(read_varint(&mut cursor).is_err());
+    }
+
+    #[test]
+    fn split_host_port_uses_default_when_absent() {
+        let (host, port) = split_host_port("example.com", 25565).unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(port, 25565);
+    }
+
+    #[test]
+    fn split_host_port_parses_explicit_port() {
+        let (host, port) = split_host_port("example.com:27015", 25565).unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(port, 27015);
+    }
+
+    #[test]
+    fn split_host_port_rejects_invalid_port() {
+        assert!(split_host_port("example.com:notaport", 25565).is_err());
+    }
+}
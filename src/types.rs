@@ -1,5 +1,32 @@
 use serenity::model::prelude::{ChannelId, GuildId, MessageId};
 
+use crate::query::Game;
+
+/// Per-guild configuration, persisted in the `guild_settings` table. Both
+/// fields are optional so a guild that has never configured anything falls
+/// back to the global defaults.
+#[derive(Debug, Default)]
+pub struct GuildSettings {
+    pub timezone: Option<chrono_tz::Tz>,
+    pub poll_interval_seconds: Option<u64>,
+}
+
+/// A single recorded data point of a server's population over time.
+#[derive(Debug)]
+pub struct PlayerCountRecord {
+    pub timestamp: i64,
+    pub player_count: u32,
+    pub map: Option<String>,
+}
+
+/// A single guild setting to write. Each variant maps to one column in
+/// `guild_settings`.
+#[derive(Debug)]
+pub enum GuildSetting {
+    Timezone(chrono_tz::Tz),
+    PollInterval(Option<u64>),
+}
+
 #[derive(Debug)]
 pub struct Subscription {
     pub id: Option<i64>,
@@ -7,4 +34,11 @@ pub struct Subscription {
     pub channel_id: ChannelId,
     pub message_id: MessageId,
     pub server_hostname: String,
+    pub game: Game,
+    pub notify_players: bool,
+    pub consecutive_failures: u32,
+    /// Whether the server was up on the previous poll.
+    pub last_status: bool,
+    /// Unix timestamp of the last successful poll, used to report downtime.
+    pub last_success_at: Option<i64>,
 }